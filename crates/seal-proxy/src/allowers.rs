@@ -1,26 +1,355 @@
-use crate::{BearerToken, Allower};
-use std::collections::HashSet;
-use crate::config::{load, BearerTokenConfig};
+use crate::{BearerToken, Grantor};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::config::{load_bearer_token_config, Grant, IntrospectionConfig};
 use anyhow::Result;
+use arc_swap::ArcSwap;
+use dashmap::DashMap;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use prometheus::{register_int_counter_with_registry, IntCounter, Registry};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 
+/// how often we re-read the tokens file even without a filesystem notification, as a fallback
+/// for watches that never fire (eg some network filesystems, or `notify` failing to initialize).
+const FALLBACK_RELOAD_INTERVAL: Duration = Duration::from_secs(30);
+
+/// prefix marking a `bearer_token` config value as an already-computed digest rather than the
+/// raw token, eg `sha256$<hex>`.
+const DIGEST_PREFIX: &str = "sha256$";
+
+/// hashes a raw bearer token into the `sha256$<hex>` digest form `BearerTokenConfigItem` accepts
+/// in place of a raw token, so operators can generate a value for the tokens file without ever
+/// writing the credential itself to disk.
+pub fn hash_bearer_token(token: &str) -> String {
+    format!("{DIGEST_PREFIX}{}", hex::encode(Sha256::digest(token.as_bytes())))
+}
+
+/// the digest form of a configured `bearer_token` value: used as-is if it is already a
+/// `sha256$<hex>` digest, otherwise hashed now so the raw credential is never retained in memory
+/// past config load.
+fn digest_of_configured(bearer_token: &str) -> String {
+    if bearer_token.starts_with(DIGEST_PREFIX) {
+        bearer_token.to_string()
+    } else {
+        hash_bearer_token(bearer_token)
+    }
+}
+
+/// the validity window, grants, and attribution for a single configured token.
 #[derive(Debug, Clone)]
+struct TokenValidity {
+    /// the configured name for the token, used to attribute per-node behavior (rate limiting,
+    /// metrics) without exposing the raw token itself
+    name: String,
+    /// unix seconds before which the token is not yet valid
+    not_before: Option<i64>,
+    /// unix seconds at which the token stops being valid
+    expires_at: Option<i64>,
+    /// the scopes this token is authorized for
+    scopes: HashSet<Grant>,
+}
+
+impl TokenValidity {
+    fn is_valid_at(&self, now: i64) -> bool {
+        self.not_before.map_or(true, |nbf| now >= nbf) && self.expires_at.map_or(true, |exp| now < exp)
+    }
+}
+
+/// keyed by the sha256 digest of the bearer token (see `digest_of_configured`), never the raw
+/// token, so a leaked snapshot doesn't hand out plaintext credentials.
+#[derive(Debug)]
+struct Snapshot {
+    tokens: HashMap<BearerToken, TokenValidity>,
+}
+
+impl Snapshot {
+    fn load(path: &str) -> Result<Self> {
+        let bearer_token_config = load_bearer_token_config(path)?;
+        let tokens = bearer_token_config
+            .items
+            .into_iter()
+            .map(|item| {
+                (
+                    digest_of_configured(&item.bearer_token),
+                    TokenValidity {
+                        name: item.name,
+                        not_before: item.not_before,
+                        expires_at: item.expires_at,
+                        scopes: item.scopes.into_iter().collect(),
+                    },
+                )
+            })
+            .collect();
+        Ok(Self { tokens })
+    }
+
+    /// the soonest `expires_at` among all currently configured tokens, if any has one; callers
+    /// can use this to proactively reload shortly before a token would otherwise stop working.
+    fn next_expiry(&self) -> Option<i64> {
+        self.tokens.values().filter_map(|validity| validity.expires_at).min()
+    }
+}
+
+/// Prometheus counters for a config-file reload loop.
+struct ReloadMetrics {
+    reloads: IntCounter,
+    errors: IntCounter,
+}
+
+impl ReloadMetrics {
+    fn new(registry: &Registry, component: &str) -> Self {
+        let reloads = register_int_counter_with_registry!(
+            format!("{component}_config_reloads_total"),
+            format!("successful config reloads for {component}"),
+            registry
+        )
+        .expect("unable to register config reloads counter");
+        let errors = register_int_counter_with_registry!(
+            format!("{component}_config_reload_errors_total"),
+            format!("config reloads for {component} that failed and kept the previous config"),
+            registry
+        )
+        .expect("unable to register config reload errors counter");
+        Self { reloads, errors }
+    }
+}
+
+/// the live bearer-token allowlist. `new` reads `bearer_tokens_path` once; `spawn_reloader`
+/// additionally watches that file for changes and atomically swaps in a freshly parsed
+/// `BearerTokenConfig`, so in-flight requests keep seeing a single consistent snapshot and
+/// operators can rotate credentials without restarting the proxy. This is the
+/// `Arc<HashSet<BearerToken>>`-behind-a-lock hot reload you'd otherwise reach for, just with the
+/// `ArcSwap<Snapshot>` it already had to become once tokens gained validity windows and scopes
+/// ([`TokenValidity`]) instead of being plain set members; a reload that fails to parse or falls
+/// outside the supported config version (see `config::load_bearer_token_config`) is logged via
+/// `ReloadMetrics`/`tracing::warn!` and the previous good snapshot is left in place untouched.
+#[derive(Debug)]
 pub struct BearerTokenProvider {
-    bearer_tokens: HashSet<BearerToken>,
+    path: String,
+    snapshot: ArcSwap<Snapshot>,
 }
 
 impl BearerTokenProvider {
     pub fn new(bearer_token_config_path: Option<String>) -> Result<Option<Self>> {
-        if bearer_token_config_path.is_none() {
+        let Some(path) = bearer_token_config_path else {
             return Ok(None);
+        };
+
+        let snapshot = Snapshot::load(&path)?;
+        Ok(Some(Self { path, snapshot: ArcSwap::from_pointee(snapshot) }))
+    }
+
+    /// unix seconds of the soonest `expires_at` among all configured tokens (including ones that
+    /// have already expired), if any has one.
+    /// Callers that want to rotate ahead of an expiry (rather than rely solely on
+    /// `spawn_reloader`'s periodic re-read) can poll this to know when a proactive reload is due.
+    pub fn next_expiry(&self) -> Option<i64> {
+        self.snapshot.load().next_expiry()
+    }
+
+    /// watches `path` for modifications and atomically swaps in a freshly parsed
+    /// `BearerTokenConfig` whenever one parses successfully. A file that fails to parse is
+    /// logged and skipped, keeping the previous good snapshot live rather than dropping auth for
+    /// every node; a periodic re-read runs alongside the watch in case the underlying
+    /// filesystem never delivers a change notification.
+    pub fn spawn_reloader(self: &Arc<Self>, cancel: CancellationToken, registry: &Registry) -> JoinHandle<()> {
+        let provider = self.clone();
+        let metrics = ReloadMetrics::new(registry, "bearer_token");
+        let (tx, mut rx) = mpsc::channel(1);
+
+        // notify's watcher callback runs on its own thread; forward a signal into the async
+        // world over a channel so the reload loop below can `select!` on it alongside the
+        // fallback timer and cancellation.
+        let watcher: Option<RecommendedWatcher> =
+            match notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                if event.is_ok() {
+                    let _ = tx.blocking_send(());
+                }
+            }) {
+                Ok(mut watcher) => {
+                    match watcher.watch(Path::new(&provider.path), RecursiveMode::NonRecursive) {
+                        Ok(()) => Some(watcher),
+                        Err(error) => {
+                            tracing::warn!(path = %provider.path, %error, "unable to watch bearer tokens file, falling back to periodic reload only");
+                            None
+                        }
+                    }
+                }
+                Err(error) => {
+                    tracing::warn!(%error, "unable to create bearer tokens file watcher, falling back to periodic reload only");
+                    None
+                }
+            };
+
+        tokio::spawn(async move {
+            // keep the watcher alive for the lifetime of this task; dropping it stops the watch.
+            let _watcher = watcher;
+            let mut fallback = tokio::time::interval(FALLBACK_RELOAD_INTERVAL);
+            fallback.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+            loop {
+                tokio::select! {
+                    _ = rx.recv() => {}
+                    _ = fallback.tick() => {}
+                    _ = cancel.cancelled() => {
+                        tracing::info!("received cancellation request, shutting down bearer token reloader");
+                        return;
+                    }
+                }
+
+                match Snapshot::load(&provider.path) {
+                    Ok(snapshot) => {
+                        provider.snapshot.store(Arc::new(snapshot));
+                        metrics.reloads.inc();
+                        tracing::info!(path = %provider.path, "reloaded bearer token allowlist");
+                    }
+                    Err(error) => {
+                        metrics.errors.inc();
+                        tracing::warn!(path = %provider.path, %error, "failed to reload bearer token allowlist, keeping previous config");
+                    }
+                }
+            }
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Grantor<BearerToken> for BearerTokenProvider {
+    /// empty for an unknown token, and also for a known one outside its configured
+    /// `not_before`/`expires_at` window, so short-lived credentials stop working on schedule
+    /// without requiring a reload to remove them. Looks the token up by its sha256 digest rather
+    /// than the raw value, so the lookup never compares the raw credential byte-for-byte.
+    async fn grants(&self, key: &BearerToken) -> HashSet<Grant> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        self.snapshot
+            .load()
+            .tokens
+            .get(&hash_bearer_token(key))
+            .filter(|validity| validity.is_valid_at(now))
+            .map(|validity| validity.scopes.clone())
+            .unwrap_or_default()
+    }
+
+    /// the configured name for `token`, if it is known
+    fn name_for(&self, key: &BearerToken) -> Option<String> {
+        self.snapshot.load().tokens.get(&hash_bearer_token(key)).map(|validity| validity.name.clone())
+    }
+}
+
+/// an RFC 7662 introspection response. Only the fields we act on are modeled; the authorization
+/// server is free to return others (`username`, ...) and we ignore them.
+#[derive(Debug, Deserialize)]
+struct IntrospectionResponse {
+    active: bool,
+    /// unix seconds the token expires at, used to derive how long we may cache the decision
+    #[serde(default)]
+    exp: Option<i64>,
+    /// space-delimited grants per RFC 7662; an inactive token's scope (if any) is ignored
+    #[serde(default)]
+    scope: Option<String>,
+}
+
+/// a cached introspection decision, valid until `expires_at`.
+struct CacheEntry {
+    grants: HashSet<Grant>,
+    expires_at: Instant,
+}
+
+/// validates bearer tokens against a remote RFC 7662 token introspection endpoint instead of a
+/// locally known set, so `seal` can front opaque tokens issued by an external IdP without every
+/// valid token needing to be pre-listed locally. An `active` decision is cached by the raw token
+/// for the lifetime implied by the response's `exp` claim (or `default_cache_ttl` when absent) to
+/// avoid a network round-trip on every request; network errors, unparsable responses, inactive
+/// decisions, and active decisions with an `exp` already in the past are all treated as a deny
+/// without being cached, so a token denied this request is re-checked against the IdP on the next
+/// one instead of staying denied (or, worse, wrongly cached as valid) for a full TTL.
+pub struct IntrospectionProvider {
+    config: IntrospectionConfig,
+    client: reqwest::Client,
+    cache: DashMap<BearerToken, CacheEntry>,
+}
+
+impl IntrospectionProvider {
+    pub fn new(config: IntrospectionConfig) -> Self {
+        Self { config, client: reqwest::Client::new(), cache: DashMap::new() }
+    }
+
+    async fn introspect(&self, token: &BearerToken) -> HashSet<Grant> {
+        let response = match self
+            .client
+            .post(&self.config.introspection_endpoint)
+            .basic_auth(&self.config.client_id, Some(&self.config.client_secret))
+            .form(&[("token", token.as_str()), ("token_type_hint", "access_token")])
+            .send()
+            .await
+        {
+            Ok(response) => response,
+            Err(error) => {
+                tracing::warn!(%error, "token introspection request failed, denying");
+                return HashSet::new();
+            }
+        };
+
+        let body: IntrospectionResponse = match response.json().await {
+            Ok(body) => body,
+            Err(error) => {
+                tracing::warn!(%error, "unable to parse token introspection response, denying");
+                return HashSet::new();
+            }
+        };
+
+        // an inactive token carries no `exp` to cache against and may become active again at the
+        // IdP at any moment (eg once the holder completes a pending re-authorization), so we deny
+        // it for this request without caching the decision rather than pinning it to a deny for
+        // up to `default_cache_ttl`.
+        if !body.active {
+            return HashSet::new();
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        if body.exp.is_some_and(|exp| exp <= now) {
+            // the IdP says active but exp is already in the past -- don't fall back to
+            // default_cache_ttl here, since that would cache an already-expired token as valid
+            // for a full TTL; deny it immediately without caching, same as an inactive token.
+            return HashSet::new();
         }
 
-        let bearer_token_config: BearerTokenConfig = load(bearer_token_config_path.unwrap())?;
-        Ok(Some(Self { bearer_tokens: bearer_token_config.items.iter().map(|item| item.bearer_token.clone()).collect() }))
+        let ttl = body
+            .exp
+            .and_then(|exp| u64::try_from(exp - now).ok())
+            .map(Duration::from_secs)
+            .filter(|ttl| !ttl.is_zero())
+            .unwrap_or(self.config.default_cache_ttl);
+
+        // an authorization server that returns `active` with no `scope` is assumed to speak the
+        // plain yes/no dialect of RFC 7662; treat that as "authorized for everything" rather than
+        // "authorized for nothing" so such servers keep working the way they did before scopes.
+        let grants: HashSet<Grant> = match &body.scope {
+            Some(scope) => scope.split_whitespace().filter_map(Grant::parse_rfc7662_scope).collect(),
+            None => [Grant::AccessToken, Grant::RefreshToken, Grant::LiveMetrics, Grant::LiveTracing]
+                .into_iter()
+                .collect(),
+        };
+
+        self.cache.insert(token.clone(), CacheEntry { grants: grants.clone(), expires_at: Instant::now() + ttl });
+        grants
     }
 }
 
-impl Allower<BearerToken> for BearerTokenProvider {
-    fn allowed(&self, key: &BearerToken) -> bool {
-        self.bearer_tokens.contains(key)
+#[async_trait::async_trait]
+impl Grantor<BearerToken> for IntrospectionProvider {
+    async fn grants(&self, key: &BearerToken) -> HashSet<Grant> {
+        if let Some(entry) = self.cache.get(key) {
+            if entry.expires_at > Instant::now() {
+                return entry.grants.clone();
+            }
+        }
+        self.introspect(key).await
     }
-}
\ No newline at end of file
+}
@@ -7,7 +7,7 @@ use serde_with::{serde_as};
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use tracing::debug;
 use crate::BearerToken;
-use serde_with::DurationSeconds;
+use serde_with::{DurationMilliSeconds, DurationSeconds};
 use std::time::Duration;
 
 #[serde_as]
@@ -17,14 +17,86 @@ pub struct ProxyConfig {
     /// Sets the maximum idle connection per host allowed in the pool.
     #[serde(default = "pool_max_idle_per_host_default")]
     pub pool_max_idle_per_host: usize,
-    #[serde(default = "mimir_url_default")]
-    pub mimir_url: String,
+    /// the remote-write targets we relay decoded metrics to. Defaults to a single failover
+    /// target so existing single-upstream deployments keep working unmodified.
+    #[serde(default = "upstreams_default")]
+    pub upstreams: Vec<UpstreamConfig>,
     /// what address to bind to
     #[serde(default = "listen_address_default")]
     pub listen_address: String,
     /// metrics address for the service itself
     #[serde(default = "metrics_address_default")]
     pub metrics_address: String,
+    /// labels applied to every series relayed to mimir, merged in on top of whatever labels the
+    /// pushing node already attached (without overwriting them)
+    #[serde(default)]
+    pub global_labels: Option<HashMap<String, String>>,
+    /// per-token request rate limiting; unset disables rate limiting entirely
+    #[serde(default)]
+    pub rate_limit: Option<RateLimitConfig>,
+    /// retry policy applied to each attempt to push to an upstream
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// PEM certificate chain used to terminate TLS on the ingest/metrics listeners; when unset
+    /// (along with `tls_key_path`) the proxy serves plaintext HTTP
+    #[serde(default)]
+    pub tls_cert_path: Option<String>,
+    /// PEM private key matching `tls_cert_path`
+    #[serde(default)]
+    pub tls_key_path: Option<String>,
+    /// PEM CA bundle used to verify client certificates; when set, mutual TLS is required and
+    /// only clients presenting a certificate signed by this CA may connect
+    #[serde(default)]
+    pub tls_client_ca_path: Option<String>,
+    /// maximum age a signed node push's timestamp may have before `node_auth::verify_node_signature`
+    /// rejects it as a possible replay
+    #[serde_as(as = "DurationSeconds<u64>")]
+    #[serde(rename = "node_signature_max_skew_secs", default = "node_signature_max_skew_default")]
+    pub node_signature_max_skew: Duration,
+}
+
+fn node_signature_max_skew_default() -> Duration {
+    Duration::from_secs(30)
+}
+
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct RetryConfig {
+    /// how many times to retry a failed upstream push before giving up
+    #[serde(default = "retry_max_retries_default")]
+    pub max_retries: u32,
+    /// the smallest delay used between retries (and the starting point for backoff)
+    #[serde_as(as = "DurationMilliSeconds<u64>")]
+    #[serde(rename = "base_delay_ms", default = "retry_base_delay_default")]
+    pub base_delay: Duration,
+    /// the largest backoff delay computed between retries; an upstream `Retry-After` header is
+    /// honored as given, even when it is longer
+    #[serde_as(as = "DurationMilliSeconds<u64>")]
+    #[serde(rename = "max_delay_ms", default = "retry_max_delay_default")]
+    pub max_delay: Duration,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: retry_max_retries_default(),
+            base_delay: retry_base_delay_default(),
+            max_delay: retry_max_delay_default(),
+        }
+    }
+}
+
+fn retry_max_retries_default() -> u32 {
+    5
+}
+
+fn retry_base_delay_default() -> Duration {
+    Duration::from_millis(200)
+}
+
+fn retry_max_delay_default() -> Duration {
+    Duration::from_secs(5)
 }
 
 /// the default idle worker per host (reqwest to remote write url call)
@@ -32,9 +104,47 @@ fn pool_max_idle_per_host_default() -> usize {
     8
 }
 
-/// the default mimir url
-fn mimir_url_default() -> String {
-    "http://localhost:9000/api/v1/metrics/write".to_string()
+/// how a single upstream participates in routing: `Mirror` targets always receive a copy of
+/// every push, `Failover` targets are tried in priority (weight) order and only one needs to
+/// succeed.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum UpstreamMode {
+    Mirror,
+    Failover,
+}
+
+impl Default for UpstreamMode {
+    fn default() -> Self {
+        UpstreamMode::Failover
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct UpstreamConfig {
+    /// remote-write endpoint url, eg a mimir `/api/v1/metrics/write`
+    pub url: String,
+    /// relative priority among `Failover` targets in the same group (higher goes first); has no
+    /// effect on `Mirror` targets, which all always receive a copy
+    #[serde(default = "upstream_weight_default")]
+    pub weight: u32,
+    #[serde(default)]
+    pub mode: UpstreamMode,
+}
+
+fn upstream_weight_default() -> u32 {
+    100
+}
+
+/// the default set of upstreams: a single failover target pointing at the previous default
+/// mimir url, so existing single-upstream deployments keep working unmodified.
+fn upstreams_default() -> Vec<UpstreamConfig> {
+    vec![UpstreamConfig {
+        url: "http://localhost:9000/api/v1/metrics/write".to_string(),
+        weight: upstream_weight_default(),
+        mode: UpstreamMode::Failover,
+    }]
 }
 
 fn listen_address_default() -> String {
@@ -45,19 +155,167 @@ fn metrics_address_default() -> String {
     "0.0.0.0:9185".to_string()
 }
 
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RateLimitConfig {
+    /// maximum number of requests a single token may make within `window`
+    #[serde(default = "rate_limit_max_requests_default")]
+    pub max_requests: u64,
+    /// the fixed window a token's request count is measured over
+    #[serde_as(as = "DurationSeconds<u64>")]
+    #[serde(rename = "window_secs", default = "rate_limit_window_default")]
+    pub window: Duration,
+    /// optional redis connection url used to share counts across proxy replicas; when unset (or
+    /// unreachable at request time) the limiter degrades to a purely local, per-process count
+    #[serde(default)]
+    pub redis_url: Option<String>,
+    /// how many local requests to admit between syncs of the local count with redis
+    #[serde(default = "rate_limit_redis_sync_every_default")]
+    pub redis_sync_every: u64,
+}
+
+fn rate_limit_max_requests_default() -> u64 {
+    600
+}
+
+fn rate_limit_window_default() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn rate_limit_redis_sync_every_default() -> u64 {
+    10
+}
+
+/// a scope a bearer token may be authorized for, modeled loosely on OAuth2 token/grant types. A
+/// token's `Grantor::grants` is the subset of these it is allowed to use; downstream services
+/// can refuse a request whose token lacks the grant a given endpoint requires (eg a metrics-only
+/// token hitting a tracing API).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Grant {
+    AccessToken,
+    RefreshToken,
+    LiveMetrics,
+    LiveTracing,
+}
+
+impl Grant {
+    /// parses a single space-delimited scope value from an RFC 7662 introspection response (eg
+    /// `"live_metrics"`) into the matching `Grant`; scopes we don't recognize are ignored rather
+    /// than rejecting the whole response.
+    pub fn parse_rfc7662_scope(scope: &str) -> Option<Self> {
+        match scope {
+            "access_token" => Some(Grant::AccessToken),
+            "refresh_token" => Some(Grant::RefreshToken),
+            "live_metrics" => Some(Grant::LiveMetrics),
+            "live_tracing" => Some(Grant::LiveTracing),
+            _ => None,
+        }
+    }
+}
+
+/// the full set of grants a token configured without an explicit `scopes` list is given, so
+/// existing `bearer-tokens.yaml` files (written before scopes existed) keep behaving like the
+/// plain yes/no allowlist they were.
+fn all_grants_default() -> Vec<Grant> {
+    vec![Grant::AccessToken, Grant::RefreshToken, Grant::LiveMetrics, Grant::LiveTracing]
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct BearerTokenConfigItem {
     pub bearer_token: BearerToken,
     pub name: String,
+    /// unix seconds before which this token is not yet valid; unset means it's valid immediately
+    #[serde(default)]
+    pub not_before: Option<i64>,
+    /// unix seconds at which this token stops being valid; unset means it never expires
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+    /// the grants this token is authorized for; defaults to every grant so a config file written
+    /// before scopes existed keeps granting everything it used to
+    #[serde(default = "all_grants_default")]
+    pub scopes: Vec<Grant>,
+}
+
+/// the only `BearerTokenConfig.version` major version this binary understands; a file written
+/// for a different major version is rejected by `load_bearer_token_config` rather than silently
+/// misinterpreted.
+const BEARER_TOKEN_CONFIG_SUPPORTED_MAJOR: u64 = 1;
+
+fn bearer_token_config_version_default() -> String {
+    "1.0.0".to_string()
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct BearerTokenConfig {
+    /// the schema version this file was written for, checked against
+    /// `BEARER_TOKEN_CONFIG_SUPPORTED_MAJOR`. Defaults to the current major version so files
+    /// written before this field existed are treated as compatible.
+    #[serde(default = "bearer_token_config_version_default")]
+    pub version: String,
     pub items: Vec<BearerTokenConfigItem>,
 }
 
+/// an error loading a `BearerTokenConfig`, distinguishing a file that simply doesn't parse from
+/// one that parses fine but is for an incompatible schema version -- the latter gets an
+/// actionable message instead of looking like a generic parse failure.
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("{path} is for bearer token config version {found}, but this binary only supports major version {supported}")]
+    IncompatibleVersion { path: String, found: String, supported: u64 },
+    #[error("{path} could not be parsed")]
+    ConfigCorrupted {
+        path: String,
+        #[source]
+        source: anyhow::Error,
+    },
+}
+
+/// loads and validates a `BearerTokenConfig` from `path`, rejecting a config written for an
+/// incompatible major version (see `ConfigError::IncompatibleVersion`) instead of misinterpreting
+/// a format it doesn't understand.
+pub fn load_bearer_token_config<P: AsRef<std::path::Path>>(path: P) -> Result<BearerTokenConfig> {
+    let path_str = path.as_ref().display().to_string();
+    let config: BearerTokenConfig =
+        load(&path).map_err(|source| ConfigError::ConfigCorrupted { path: path_str.clone(), source })?;
+
+    let found_major = config.version.split('.').next().and_then(|major| major.parse::<u64>().ok());
+    if found_major != Some(BEARER_TOKEN_CONFIG_SUPPORTED_MAJOR) {
+        return Err(ConfigError::IncompatibleVersion {
+            path: path_str,
+            found: config.version.clone(),
+            supported: BEARER_TOKEN_CONFIG_SUPPORTED_MAJOR,
+        }
+        .into());
+    }
+
+    Ok(config)
+}
+
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct IntrospectionConfig {
+    /// RFC 7662 token introspection endpoint on the authorization server
+    pub introspection_endpoint: String,
+    /// client id this proxy authenticates itself to the authorization server with, via HTTP
+    /// Basic auth
+    pub client_id: String,
+    pub client_secret: String,
+    /// how long to cache an "active" decision when the introspection response has no `exp` claim
+    /// to derive a TTL from; a response whose `exp` is not an integer fails to parse and is denied
+    #[serde_as(as = "DurationSeconds<u64>")]
+    #[serde(rename = "default_cache_ttl_secs", default = "introspection_default_cache_ttl_default")]
+    pub default_cache_ttl: Duration,
+}
+
+fn introspection_default_cache_ttl_default() -> Duration {
+    Duration::from_secs(60)
+}
+
 /// load our config file from a path
 pub fn load<P: AsRef<std::path::Path>, T: DeserializeOwned + Serialize>(path: P) -> Result<T> {
     let path = path.as_ref();
@@ -84,6 +342,9 @@ pub struct MetricsPushConfig {
     /// Static labels to provide to the push process.
     #[serde(default, skip_serializing_if = "is_none")]
     pub labels: Option<HashMap<String, String>>,
+    /// Retry policy applied to a failed push.
+    #[serde(default)]
+    pub retry: RetryConfig,
 }
 
 /// Configure the default push interval for metrics.
@@ -2,17 +2,25 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use axum::{
-    extract::Request, http::{Method, HeaderMap}, Extension,
+    extract::Request, Extension,
     body::to_bytes,
     http::StatusCode,
 };
-use reqwest::header::{HeaderMap as ReqwestHeaderMap, HeaderValue, HeaderName};
 
-use std::time::Duration;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use crate::var;
 use serde::{Deserialize, Serialize};
 use fastcrypto::secp256r1::Secp256r1PublicKey;
 use crate::config::ProxyConfig;
+use crate::remote_write::{Label, Sample, TimeSeries, WriteRequest};
+use crate::retry::RetryMetrics;
+use crate::runtime::MetricPayload;
+use crate::upstream::UpstreamRouter;
+use prometheus::proto::MetricFamily;
+use prometheus::Registry;
+use prost::Message as _;
+use protobuf::CodedInputStream;
 use std::sync::Arc;
 
 pub type NetworkPublicKey = Secp256r1PublicKey;
@@ -27,13 +35,17 @@ pub struct NodeInfo {
     pub network_public_key: NetworkPublicKey,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct ReqwestClient {
     pub client: reqwest::Client,
-    pub mimir_url: String,
+    pub router: Arc<UpstreamRouter>,
+    /// labels merged into every series we relay, without overwriting labels a node already set
+    pub global_labels: Option<HashMap<String, String>>,
+    pub retry: crate::config::RetryConfig,
+    pub retry_metrics: Arc<RetryMetrics>,
 }
 
-pub fn make_reqwest_client(config: Arc<ProxyConfig>, user_agent: &str) -> ReqwestClient {
+pub fn make_reqwest_client(config: Arc<ProxyConfig>, user_agent: &str, registry: &Registry) -> ReqwestClient {
     let client = reqwest::Client::builder()
         .user_agent(user_agent)
         .pool_max_idle_per_host(config.pool_max_idle_per_host)
@@ -41,60 +53,216 @@ pub fn make_reqwest_client(config: Arc<ProxyConfig>, user_agent: &str) -> Reqwes
         .build()
         .expect("cannot create reqwest client");
 
-    ReqwestClient { client, mimir_url: config.mimir_url.clone() }
+    ReqwestClient {
+        client,
+        router: Arc::new(UpstreamRouter::new(&config.upstreams, registry)),
+        global_labels: config.global_labels.clone(),
+        retry: config.retry.clone(),
+        retry_metrics: Arc::new(RetryMetrics::new(registry, "relay")),
+    }
 }
 
-/// relay handler which receives metrics from nodes.  Nodes will call us at
-/// this endpoint and we relay them to the upstream tsdb.
+/// relay handler which receives metrics from nodes. Nodes push us a snappy-compressed, JSON
+/// encoded `MetricPayload` (see `runtime::MetricPayload`) and we decode it, merge in labels, and
+/// re-encode it as a Prometheus remote-write `WriteRequest` before forwarding it via the
+/// `UpstreamRouter`: to every mirror target, and to failover targets in priority order until one
+/// accepts it.
 pub async fn relay_metrics_to_mimir(
     Extension(reqwest_client): Extension<ReqwestClient>,
     req: Request,
 ) -> Result<String, StatusCode> {
-    let (parts, body) = req.into_parts();
+    let (_parts, body) = req.into_parts();
 
-    let req_builder = reqwest_client.client.request(convert_axum_method_to_reqwest_method(parts.method), reqwest_client.mimir_url);
-    // convert the axum body to bytes
     let body_bytes = to_bytes(body, usize::MAX).await.map_err(|e| {
         tracing::error!("Error converting axum body to bytes: {}", e);
         StatusCode::BAD_GATEWAY
     })?;
-    let response = req_builder
-        .headers(convert_headers(&parts.headers))
-        .body(body_bytes)
-        .send()
-        .await.map_err(|e| {
-            tracing::error!("Error sending request: {}", e);
-            StatusCode::BAD_GATEWAY
+
+    let decompressed = snap::raw::Decoder::new()
+        .decompress_vec(&body_bytes)
+        .map_err(|e| {
+            tracing::error!("Error snappy-decompressing push body: {}", e);
+            StatusCode::BAD_REQUEST
         })?;
 
-    Ok(response.text().await.map_err(|e| {
-        tracing::error!("Error reading response text: {}", e);
-        StatusCode::BAD_GATEWAY
-    })?)
+    let payload: MetricPayload = serde_json::from_slice(&decompressed).map_err(|e| {
+        tracing::error!("Error deserializing MetricPayload: {}", e);
+        StatusCode::BAD_REQUEST
+    })?;
+
+    let families = parse_metric_families(&payload.buf).map_err(|e| {
+        tracing::error!("Error parsing protobuf-encoded MetricFamily stream: {}", e);
+        StatusCode::BAD_REQUEST
+    })?;
+
+    let write_request = build_write_request(
+        &families,
+        payload.labels.as_ref(),
+        reqwest_client.global_labels.as_ref(),
+    );
+
+    let mut encoded = Vec::new();
+    write_request
+        .encode(&mut encoded)
+        .map_err(|e| {
+            tracing::error!("Error protobuf-encoding WriteRequest: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let compressed = snap::raw::Encoder::new()
+        .compress_vec(&encoded)
+        .map_err(|e| {
+            tracing::error!("Error snappy-compressing WriteRequest: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    reqwest_client
+        .router
+        .relay(
+            &reqwest_client.client,
+            &reqwest_client.retry,
+            &reqwest_client.retry_metrics,
+            &compressed,
+        )
+        .await
+}
+
+/// parses the length-delimited stream of protobuf-encoded `MetricFamily` messages produced by
+/// `prometheus::ProtobufEncoder` back into a `Vec<MetricFamily>`.
+fn parse_metric_families(buf: &[u8]) -> Result<Vec<MetricFamily>, protobuf::Error> {
+    let mut input = CodedInputStream::from_bytes(buf);
+    let mut families = Vec::new();
+    while !input.eof()? {
+        families.push(input.read_message::<MetricFamily>()?);
+    }
+    Ok(families)
 }
 
-fn convert_axum_method_to_reqwest_method(method: Method) -> reqwest::Method {
-    match method {
-        Method::GET => reqwest::Method::GET,
-        Method::POST => reqwest::Method::POST,
-        Method::PUT => reqwest::Method::PUT,
-        Method::DELETE => reqwest::Method::DELETE,
-        Method::HEAD => reqwest::Method::HEAD,
-        _ => panic!("Unsupported method: {}", method),
+/// merges `payload_labels` and `global_labels` into every sample's label set (without
+/// overwriting labels the sample already carries) and flattens the resulting families into
+/// remote-write `TimeSeries`.
+fn build_write_request(
+    families: &[MetricFamily],
+    payload_labels: Option<&HashMap<String, String>>,
+    global_labels: Option<&HashMap<String, String>>,
+) -> WriteRequest {
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64;
+
+    let mut timeseries = Vec::new();
+    for family in families {
+        for metric in family.get_metric() {
+            let mut labels: HashMap<String, String> = metric
+                .get_label()
+                .iter()
+                .map(|l| (l.get_name().to_string(), l.get_value().to_string()))
+                .collect();
+            for extra in [payload_labels, global_labels].into_iter().flatten() {
+                for (k, v) in extra {
+                    labels.entry(k.clone()).or_insert_with(|| v.clone());
+                }
+            }
+
+            let timestamp_ms = if metric.get_timestamp_ms() != 0 {
+                metric.get_timestamp_ms()
+            } else {
+                now_ms
+            };
+
+            timeseries.extend(metric_to_timeseries(family, metric, &labels, timestamp_ms));
+        }
     }
+
+    WriteRequest { timeseries }
 }
 
-fn convert_headers(axum_headers: &HeaderMap) -> ReqwestHeaderMap {
-    let mut reqwest_headers = ReqwestHeaderMap::new();
+fn metric_to_timeseries(
+    family: &MetricFamily,
+    metric: &prometheus::proto::Metric,
+    labels: &HashMap<String, String>,
+    timestamp_ms: i64,
+) -> Vec<TimeSeries> {
+    use prometheus::proto::MetricType;
 
-    for (key, value) in axum_headers.iter() {
-        tracing::info!("header: {} = {}", key, value.to_str().unwrap_or(""));
-        if let Ok(header_name) = HeaderName::from_bytes(key.as_str().as_bytes()) {
-            if let Ok(header_value) = HeaderValue::from_bytes(value.as_bytes()) {
-                reqwest_headers.insert(header_name, header_value);
+    let name = family.get_name();
+    match family.get_field_type() {
+        MetricType::COUNTER => vec![series(name, labels, metric.get_counter().get_value(), timestamp_ms)],
+        MetricType::GAUGE => vec![series(name, labels, metric.get_gauge().get_value(), timestamp_ms)],
+        MetricType::UNTYPED => vec![series(name, labels, metric.get_untyped().get_value(), timestamp_ms)],
+        MetricType::HISTOGRAM => {
+            let histogram = metric.get_histogram();
+            let mut out = Vec::with_capacity(histogram.get_bucket().len() + 2);
+            for bucket in histogram.get_bucket() {
+                let mut bucket_labels = labels.clone();
+                bucket_labels.insert("le".to_string(), format_float(bucket.get_upper_bound()));
+                out.push(series(
+                    &format!("{name}_bucket"),
+                    &bucket_labels,
+                    bucket.get_cumulative_count() as f64,
+                    timestamp_ms,
+                ));
+            }
+            out.push(series(
+                &format!("{name}_sum"),
+                labels,
+                histogram.get_sample_sum(),
+                timestamp_ms,
+            ));
+            out.push(series(
+                &format!("{name}_count"),
+                labels,
+                histogram.get_sample_count() as f64,
+                timestamp_ms,
+            ));
+            out
+        }
+        MetricType::SUMMARY => {
+            let summary = metric.get_summary();
+            let mut out = Vec::with_capacity(summary.get_quantile().len() + 2);
+            for quantile in summary.get_quantile() {
+                let mut quantile_labels = labels.clone();
+                quantile_labels.insert("quantile".to_string(), format_float(quantile.get_quantile()));
+                out.push(series(name, &quantile_labels, quantile.get_value(), timestamp_ms));
             }
+            out.push(series(
+                &format!("{name}_sum"),
+                labels,
+                summary.get_sample_sum(),
+                timestamp_ms,
+            ));
+            out.push(series(
+                &format!("{name}_count"),
+                labels,
+                summary.get_sample_count() as f64,
+                timestamp_ms,
+            ));
+            out
         }
     }
+}
+
+fn series(name: &str, labels: &HashMap<String, String>, value: f64, timestamp_ms: i64) -> TimeSeries {
+    let mut pb_labels: Vec<Label> = vec![Label {
+        name: "__name__".to_string(),
+        value: name.to_string(),
+    }];
+    pb_labels.extend(labels.iter().map(|(k, v)| Label {
+        name: k.clone(),
+        value: v.clone(),
+    }));
 
-    reqwest_headers
-}
\ No newline at end of file
+    TimeSeries {
+        labels: pb_labels,
+        samples: vec![Sample { value, timestamp_ms }],
+    }
+}
+
+fn format_float(value: f64) -> String {
+    if value.is_infinite() {
+        if value.is_sign_negative() { "-Inf".to_string() } else { "+Inf".to_string() }
+    } else {
+        value.to_string()
+    }
+}
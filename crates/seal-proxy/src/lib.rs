@@ -0,0 +1,89 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+pub mod allowers;
+pub mod config;
+pub mod handlers;
+pub mod metrics;
+pub mod middleware;
+pub mod node_auth;
+pub mod rate_limit;
+pub mod remote_write;
+pub mod retry;
+pub mod runtime;
+pub mod server;
+pub mod tls;
+pub mod upstream;
+
+use std::collections::HashSet;
+use crate::config::Grant;
+
+/// the bearer token a node authenticates with, as sent in the `Authorization: Bearer <token>` header
+pub type BearerToken = String;
+
+/// something that can decide whether a given key (eg a bearer token) is allowed to proceed.
+/// `async` because some implementations (eg `IntrospectionProvider`) validate against a remote
+/// authorization server rather than an in-memory set. Prefer implementing `Grantor` for anything
+/// that can say *which* scopes a key holds; it comes with a blanket `Allower` impl for free.
+#[async_trait::async_trait]
+pub trait Allower<K>: Send + Sync {
+    async fn allowed(&self, key: &K) -> bool;
+
+    /// a human-readable name for `key`, if this implementation tracks one; used to attribute
+    /// per-node behavior (rate limiting, metrics) without exposing the raw key itself. Defaults
+    /// to unknown, since not every implementation (eg remote introspection) has one.
+    fn name_for(&self, _key: &K) -> Option<String> {
+        None
+    }
+}
+
+/// something that can report the set of grants (scopes) a key is authorized for, rather than a
+/// plain yes/no. Lets downstream services make per-endpoint authorization decisions, eg refusing
+/// a metrics-only token on a tracing API.
+#[async_trait::async_trait]
+pub trait Grantor<K>: Send + Sync {
+    /// the grants `key` is authorized for; an empty set means it is authorized for nothing.
+    async fn grants(&self, key: &K) -> HashSet<Grant>;
+
+    /// a human-readable name for `key`, if this implementation tracks one.
+    fn name_for(&self, _key: &K) -> Option<String> {
+        None
+    }
+}
+
+/// compatibility shim: any `Grantor` is usable wherever a plain yes/no `Allower` is expected
+/// (eg the existing bearer-token middleware) -- a non-empty grant set means allowed.
+#[async_trait::async_trait]
+impl<K: Send + Sync, T: Grantor<K>> Allower<K> for T {
+    async fn allowed(&self, key: &K) -> bool {
+        !self.grants(key).await.is_empty()
+    }
+
+    fn name_for(&self, key: &K) -> Option<String> {
+        Grantor::name_for(self, key)
+    }
+}
+
+/// reads an environment variable and parses it, falling back to `default` when unset or
+/// unparsable
+#[macro_export]
+macro_rules! var {
+    ($key:expr, $default:expr) => {
+        match std::env::var($key) {
+            Ok(val) => val.parse().unwrap_or($default),
+            Err(_) => $default,
+        }
+    };
+}
+
+/// Defines the `GIT_REVISION` and `VERSION` consts for the binary that invokes this macro.
+#[macro_export]
+macro_rules! bin_version {
+    () => {
+        const GIT_REVISION: &str = match option_env!("GIT_REVISION") {
+            Some(revision) => revision,
+            None => "unknown",
+        };
+        const VERSION: &str = const_str::concat!(env!("CARGO_PKG_VERSION"), "-", GIT_REVISION);
+    };
+}
@@ -3,14 +3,20 @@ use std::net::SocketAddr;
 use seal_proxy::metrics;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use seal_proxy::{
-    config::{load, ProxyConfig},
-    server::app,
-    allowers::BearerTokenProvider,
+    config::{load, IntrospectionConfig, ProxyConfig},
+    server::{app, serve},
+    allowers::{hash_bearer_token, BearerTokenProvider, IntrospectionProvider},
     handlers::make_reqwest_client,
+    node_auth::{FileNodeRegistry, NodeRegistry},
+    rate_limit::TokenRateLimiter,
+    runtime::MetricsRuntime,
+    tls::load_tls_config,
+    Allower, BearerToken,
 };
 use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct Metrics {
@@ -51,10 +57,39 @@ struct Args {
         help = "Specify the bearer tokens file path to use"
     )]
     bearer_tokens_path: Option<String>,
+    #[arg(
+        long,
+        help = "Specify an RFC 7662 introspection config file path to validate bearer tokens \
+                against a remote authorization server instead of --bearer-tokens-path"
+    )]
+    introspection_config_path: Option<String>,
+    #[arg(
+        long,
+        help = "Specify the authorized seal node registry file path to use for signature-based auth"
+    )]
+    node_registry_path: Option<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Hash a raw bearer token the same way `BearerTokenProvider` does, so operators can
+    /// populate the bearer tokens file with a `sha256$<hex>` digest instead of the raw token.
+    HashToken {
+        /// the raw token to hash
+        token: String,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args = Args::parse();
+    if let Some(Command::HashToken { token }) = args.command {
+        println!("{}", hash_bearer_token(&token));
+        return Ok(());
+    }
+
     // Initialize tracing
     tracing_subscriber::registry()
         .with(tracing_subscriber::EnvFilter::new(
@@ -63,31 +98,69 @@ async fn main() -> Result<()> {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    let _registry_guard = metrics::seal_proxy_prom_registry();
+    let registry = metrics::seal_proxy_prom_registry();
 
-    let args = Args::parse();
     let config: Arc<ProxyConfig> = Arc::new(load(&args.config)?);
-    let reqwest_client = make_reqwest_client(config.clone(), &APP_USER_AGENT);
+    let reqwest_client = make_reqwest_client(config.clone(), &APP_USER_AGENT, &registry);
+
+    // at most one bearer-token allower is active: a remote introspection endpoint takes
+    // priority over a locally-listed tokens file when both are configured.
+    let bearer_token_reload_cancel = CancellationToken::new();
+    let allower: Option<Arc<dyn Allower<BearerToken>>> = if let Some(path) = args.introspection_config_path {
+        let introspection_config: IntrospectionConfig = load(&path).map_err(|e| {
+            tracing::error!("error loading introspection config: {}", e);
+            e
+        })?;
+        Some(Arc::new(IntrospectionProvider::new(introspection_config)) as Arc<dyn Allower<BearerToken>>)
+    } else {
+        // if bearer tokens path is not provided, don't create a bearer token provider
+        // if the bearer tokens path is provided but the file is not found or is invalid, return an error
+        match BearerTokenProvider::new(args.bearer_tokens_path) {
+            Ok(Some(provider)) => {
+                let provider = Arc::new(provider);
+                // watch the tokens file for changes so credentials can be rotated without a
+                // restart; the reload loop is cancelled once the server below returns (after a
+                // graceful shutdown on the plaintext path; the TLS path returns only on error).
+                provider.spawn_reloader(bearer_token_reload_cancel.clone(), &registry);
+                Some(provider as Arc<dyn Allower<BearerToken>>)
+            }
+            Ok(None) => None,
+            Err(e) => {
+                tracing::error!("error creating bearer token provider: {}", e);
+                return Err(e);
+            }
+        }
+    };
 
-    // if bearer tokens path is not provided, don't create a bearer token provider
-    // if the bearer tokens path is provided but the file is not found or is invalid, return an error
-    let allower = match BearerTokenProvider::new(args.bearer_tokens_path) {
-        Ok(allower) => allower,
+    let rate_limiter = config
+        .rate_limit
+        .clone()
+        .map(|rl_config| Arc::new(TokenRateLimiter::new(rl_config, &registry)));
+
+    // like the bearer token provider, the node registry is optional: nodes that authenticate
+    // with a signed push instead of (or in addition to) a bearer token need it configured
+    let node_registry = match FileNodeRegistry::new(args.node_registry_path) {
+        Ok(node_registry) => node_registry.map(|r| Arc::new(r) as Arc<dyn NodeRegistry>),
         Err(e) => {
-            tracing::error!("error creating bearer token provider: {}", e);
+            tracing::error!("error creating node registry: {}", e);
             return Err(e);
         }
     };
 
     // Build our application with a route
-    let app = app(reqwest_client, allower);
+    let app = app(reqwest_client, allower, rate_limiter, node_registry, config.node_signature_max_skew);
 
     // Run it
     let addr = config.listen_address.parse::<SocketAddr>()?;
-    tracing::info!("listening on {}", addr);
-    
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
-    
+    let tls_config = load_tls_config(&config)?;
+
+    // serve /metrics on its own listener (and, per config, behind the same TLS termination as
+    // the ingest server), backed by the same registry everything above already publishes into.
+    let metrics_address = config.metrics_address.parse::<SocketAddr>()?;
+    let _metrics_runtime = MetricsRuntime::start(metrics_address, tls_config.clone(), registry)?;
+
+    serve(addr, app, tls_config).await?;
+    bearer_token_reload_cancel.cancel();
+
     Ok(())
 }
@@ -0,0 +1,15 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use once_cell::sync::Lazy;
+use prometheus::Registry;
+
+/// the process-wide registry the proxy's components register their metrics into via the
+/// `*_with_registry` macros
+pub static SEAL_PROXY_REGISTRY: Lazy<Registry> = Lazy::new(Registry::new);
+
+/// returns a clone of the process-wide registry for wiring into the components that register
+/// metrics and into the metrics http server.
+pub fn seal_proxy_prom_registry() -> Registry {
+    SEAL_PROXY_REGISTRY.clone()
+}
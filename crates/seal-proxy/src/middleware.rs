@@ -8,28 +8,62 @@ use axum::{
     middleware::Next,
 };
 use std::sync::Arc;
-use crate::allowers::BearerTokenProvider;
-use crate::Allower;
+use crate::node_auth::{verify_node_signature, MaxSkew, NodeRegistry};
+use crate::{Allower, BearerToken};
 
-/// we expect that calling seal nodes have known bearer tokens
-pub async fn expect_valid_bearer_token(
-    Extension(allower): Extension<Arc<BearerTokenProvider>>,
-    req: Request<Body>,
+/// the name configured for a successfully authenticated token, attached as a request extension
+/// so downstream middleware (eg rate limiting) and handlers can attribute the request without
+/// re-parsing the `Authorization` header.
+#[derive(Debug, Clone)]
+pub struct TokenName(pub String);
+
+/// accepts a request authenticated by *either* a valid bearer token *or* a valid node signature,
+/// so a mixed fleet can have legacy nodes keep presenting a shared bearer token while newer nodes
+/// authenticate with their on-chain secp256r1 key instead -- configuring one mechanism must not
+/// require every node to also satisfy the other. Only rejects with 401 once neither check passes.
+pub async fn expect_valid_bearer_token_or_node_signature(
+    Extension(allower): Extension<Option<Arc<dyn Allower<BearerToken>>>>,
+    Extension(node_registry): Extension<Option<Arc<dyn NodeRegistry>>>,
+    Extension(max_skew): Extension<MaxSkew>,
+    mut req: Request<Body>,
     next: Next,
 ) -> Result<Response, (StatusCode, &'static str)> {
-    // Extract the Authorization header
-    if let Some(auth_header) = req.headers().get(header::AUTHORIZATION) {
-        if let Ok(auth_str) = auth_header.to_str() {
-            // Check if it's a Bearer token
-            if let Some(token) = auth_str.strip_prefix("Bearer ") {
-                // Validate the token
-                if allower.allowed(&token.to_string()) {
-                    return Ok(next.run(req).await);
-                }
+    if let Some(allower) = &allower {
+        if let Some(name) = bearer_token_name(&req, allower).await {
+            if let Some(name) = name {
+                req.extensions_mut().insert(TokenName(name));
             }
+            return Ok(next.run(req).await);
+        }
+    }
+
+    if let Some(registry) = &node_registry {
+        if let Ok(req) = verify_node_signature(req, registry, max_skew).await {
+            return Ok(next.run(req).await);
         }
     }
 
-    // Reject the request if no valid token
     Err((StatusCode::UNAUTHORIZED, "Unauthorized"))
-}
\ No newline at end of file
+}
+
+/// validates the `Authorization: Bearer <token>` header against `allower`, returning `None` when
+/// the request has no valid token and `Some(name)` (the token's name, if `allower` tracks one)
+/// when it does. Used by the combined middleware above, which is the only supported entry point
+/// for bearer-token auth now.
+async fn bearer_token_name(
+    req: &Request<Body>,
+    allower: &Arc<dyn Allower<BearerToken>>,
+) -> Option<Option<String>> {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))?
+        .to_string();
+
+    if allower.allowed(&token).await {
+        Some(allower.name_for(&token))
+    } else {
+        None
+    }
+}
@@ -0,0 +1,178 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::{
+    extract::Request,
+    http::{header::RETRY_AFTER, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use dashmap::DashMap;
+use prometheus::{register_int_counter_vec_with_registry, IntCounterVec, Registry};
+
+use crate::config::RateLimitConfig;
+use crate::middleware::TokenName;
+
+/// per-token request counter for the window currently in progress.
+struct Window {
+    /// `epoch_ms / window_ms`, identifies which fixed window this count belongs to
+    key: u64,
+    count: AtomicU64,
+    /// set once a periodic redis sync reveals the token's *global* count already exceeds
+    /// `max_requests`, so every local check for the rest of the window is denied without paying
+    /// for another round-trip
+    redis_exceeded: AtomicBool,
+}
+
+/// two-tier rate limiter keyed by the resolved bearer-token name: a local, in-process counter
+/// absorbs bursts immediately, optionally backed by a shared redis fixed-window counter so
+/// multiple proxy replicas agree on a token's global usage. When redis is unconfigured or
+/// unreachable we degrade to the local counter alone.
+pub struct TokenRateLimiter {
+    config: RateLimitConfig,
+    local: DashMap<String, Window>,
+    redis: Option<redis::Client>,
+    allowed: IntCounterVec,
+    throttled: IntCounterVec,
+}
+
+impl TokenRateLimiter {
+    pub fn new(config: RateLimitConfig, registry: &Registry) -> Self {
+        let redis = config.redis_url.as_ref().and_then(|url| {
+            redis::Client::open(url.as_str())
+                .inspect_err(|error| tracing::warn!(?error, "unable to create redis client for rate limiter"))
+                .ok()
+        });
+
+        let allowed = register_int_counter_vec_with_registry!(
+            "rate_limit_allowed_total",
+            "requests allowed by the per-token rate limiter",
+            &["token_name"],
+            registry
+        )
+        .expect("unable to register rate_limit_allowed_total");
+        let throttled = register_int_counter_vec_with_registry!(
+            "rate_limit_throttled_total",
+            "requests rejected by the per-token rate limiter",
+            &["token_name"],
+            registry
+        )
+        .expect("unable to register rate_limit_throttled_total");
+
+        Self { config, local: DashMap::new(), redis, allowed, throttled }
+    }
+
+    /// returns `true` if `token_name` is still within its allowance for the current window.
+    pub async fn check(&self, token_name: &str) -> bool {
+        let window_ms = self.config.window.as_millis().max(1) as u64;
+        let window_key = now_ms() / window_ms;
+        // guard against an operator-supplied `0`, which would otherwise divide-by-zero below
+        let sync_every = self.config.redis_sync_every.max(1);
+
+        let (local_count, redis_exceeded) = {
+            let mut entry = self.local.entry(token_name.to_string()).or_insert_with(|| Window {
+                key: window_key,
+                count: AtomicU64::new(0),
+                redis_exceeded: AtomicBool::new(false),
+            });
+            if entry.key != window_key {
+                entry.key = window_key;
+                entry.count.store(0, Ordering::Relaxed);
+                entry.redis_exceeded.store(false, Ordering::Relaxed);
+            }
+            let count = entry.count.fetch_add(1, Ordering::Relaxed) + 1;
+            (count, entry.redis_exceeded.load(Ordering::Relaxed))
+        };
+
+        let mut allowed = local_count <= self.config.max_requests && !redis_exceeded;
+
+        // the local counter alone admits bursts immediately; only pay for a redis round-trip
+        // every `redis_sync_every` requests to reconcile with the token's global count across
+        // replicas, rather than on every request while under the limit.
+        if allowed && local_count % sync_every == 0 {
+            if let Some(client) = &self.redis {
+                match self.sync_redis(client, token_name, window_key, window_ms, sync_every).await {
+                    Ok(redis_count) if redis_count > self.config.max_requests => {
+                        allowed = false;
+                        if let Some(entry) = self.local.get(token_name) {
+                            entry.redis_exceeded.store(true, Ordering::Relaxed);
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(error) => {
+                        tracing::warn!(?error, "rate limiter redis sync failed, falling back to local count");
+                    }
+                }
+            }
+        }
+
+        if allowed {
+            self.allowed.with_label_values(&[token_name]).inc();
+        } else {
+            self.throttled.with_label_values(&[token_name]).inc();
+        }
+        allowed
+    }
+
+    async fn sync_redis(
+        &self,
+        client: &redis::Client,
+        token_name: &str,
+        window_key: u64,
+        window_ms: u64,
+        increment: u64,
+    ) -> redis::RedisResult<u64> {
+        use redis::AsyncCommands;
+
+        let mut conn = client.get_multiplexed_async_connection().await?;
+        let key = format!("rl:{token_name}:{window_key}");
+        // each sync stands for `increment` locally admitted requests, so the shared count
+        // tracks requests rather than syncs
+        let count: u64 = conn.incr(&key, increment).await?;
+        if count == increment {
+            let _: () = conn.pexpire(&key, window_ms as i64).await?;
+        }
+        Ok(count)
+    }
+
+    /// how long a throttled client should wait before retrying.
+    pub fn retry_after_secs(&self) -> u64 {
+        self.config.window.as_secs().max(1)
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+/// rejects requests once the calling token has exceeded its configured rate limit. Must run
+/// after `expect_valid_bearer_token_or_node_signature` so the resolved `TokenName` extension is
+/// available; requests with no resolved name are counted against a shared `"unknown"` bucket.
+pub async fn enforce_rate_limit(
+    Extension(limiter): Extension<std::sync::Arc<TokenRateLimiter>>,
+    req: Request,
+    next: Next,
+) -> Result<Response, Response> {
+    let token_name = req
+        .extensions()
+        .get::<TokenName>()
+        .map(|name| name.0.clone())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    if limiter.check(&token_name).await {
+        return Ok(next.run(req).await);
+    }
+
+    let mut response = StatusCode::TOO_MANY_REQUESTS.into_response();
+    if let Ok(value) = HeaderValue::from_str(&limiter.retry_after_secs().to_string()) {
+        response.headers_mut().insert(RETRY_AFTER, value);
+    }
+    Err(response)
+}
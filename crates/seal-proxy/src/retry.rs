@@ -0,0 +1,110 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::time::Duration;
+
+use prometheus::{register_int_counter_with_registry, IntCounter, Registry};
+use rand::Rng;
+
+use crate::config::RetryConfig;
+
+/// Prometheus counters for a single upstream push call site (eg "push" or "relay").
+pub struct RetryMetrics {
+    retries: IntCounter,
+    failures: IntCounter,
+}
+
+impl RetryMetrics {
+    pub fn new(registry: &Registry, component: &str) -> Self {
+        let retries = register_int_counter_with_registry!(
+            format!("{component}_push_retries_total"),
+            format!("retried upstream push attempts for {component}"),
+            registry
+        )
+        .expect("unable to register push retries counter");
+        let failures = register_int_counter_with_registry!(
+            format!("{component}_push_failures_total"),
+            format!("upstream pushes for {component} that exhausted retries or failed with a non-retryable send error"),
+            registry
+        )
+        .expect("unable to register push failures counter");
+        Self { retries, failures }
+    }
+}
+
+/// decorrelated-jitter backoff: the next delay is a random duration in `[base, prev * 3]`,
+/// capped at `max`. See https://aws.amazon.com/blogs/architecture/exponential-backoff-and-jitter/
+fn next_delay(base: Duration, prev: Duration, max: Duration) -> Duration {
+    let lower = base.as_millis() as u64;
+    let upper = prev.as_millis().saturating_mul(3).max(lower as u128) as u64;
+    let jittered = if upper > lower {
+        rand::thread_rng().gen_range(lower..=upper)
+    } else {
+        lower
+    };
+    Duration::from_millis(jittered).min(max)
+}
+
+/// Sends a request built by `build_request`, retrying on connection errors and on
+/// 429/500/502/503/504 responses using decorrelated-jitter exponential backoff. A `Retry-After`
+/// header on a retryable response is honored in place of the computed backoff. Any other 4xx is
+/// treated as a permanent failure and returned immediately. Rebuilds the request on every
+/// attempt via `build_request` since a sent `RequestBuilder` can't be reused.
+pub async fn send_with_retry<F>(
+    retry: &RetryConfig,
+    metrics: &RetryMetrics,
+    mut build_request: F,
+) -> Result<reqwest::Response, reqwest::Error>
+where
+    F: FnMut() -> reqwest::RequestBuilder,
+{
+    let mut attempt = 0u32;
+    let mut prev_delay = retry.base_delay;
+
+    loop {
+        match build_request().send().await {
+            Ok(response) if response.status().is_success() => return Ok(response),
+            Ok(response) => {
+                let status = response.status();
+                let retryable = matches!(status.as_u16(), 429 | 500 | 502 | 503 | 504);
+                if !retryable || attempt >= retry.max_retries {
+                    if retryable {
+                        metrics.failures.inc();
+                    }
+                    return Ok(response);
+                }
+
+                let delay = response
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.parse::<u64>().ok())
+                    .map(Duration::from_secs)
+                    .unwrap_or_else(|| next_delay(retry.base_delay, prev_delay, retry.max_delay));
+
+                attempt += 1;
+                prev_delay = delay;
+                metrics.retries.inc();
+                tracing::warn!(%status, attempt, ?delay, "retrying upstream push after retryable response");
+                tokio::time::sleep(delay).await;
+            }
+            Err(error) => {
+                if !is_connection_error(&error) || attempt >= retry.max_retries {
+                    metrics.failures.inc();
+                    return Err(error);
+                }
+
+                let delay = next_delay(retry.base_delay, prev_delay, retry.max_delay);
+                attempt += 1;
+                prev_delay = delay;
+                metrics.retries.inc();
+                tracing::warn!(?error, attempt, ?delay, "retrying upstream push after connection error");
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
+
+fn is_connection_error(error: &reqwest::Error) -> bool {
+    error.is_connect() || error.is_timeout() || error.is_request()
+}
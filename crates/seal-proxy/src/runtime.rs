@@ -1,11 +1,13 @@
 use std::{collections::HashMap, net::SocketAddr, time::{Duration, SystemTime, UNIX_EPOCH}};
 use anyhow::Context;
+use axum_server::tls_rustls::RustlsConfig;
 use tokio::{runtime::Runtime, task::JoinHandle};
 use prometheus::{Registry, Encoder, TextEncoder};
 use serde::{Deserialize, Serialize};
 use tokio_util::sync::CancellationToken;
 use axum::{extract::Extension, http::StatusCode, routing::get, Router};
 use crate::config::MetricsPushConfig;
+use crate::retry::{send_with_retry, RetryMetrics};
 use tokio::runtime;
 
 pub struct MetricsRuntime {
@@ -18,7 +20,11 @@ pub const METRICS_ROUTE: &str = "/metrics";
 
 impl MetricsRuntime {
     /// Start metrics and log collection in a new runtime
-    pub fn start(metrics_address: SocketAddr) -> anyhow::Result<Self> {
+    pub fn start(
+        metrics_address: SocketAddr,
+        tls_config: Option<RustlsConfig>,
+        registry: Registry,
+    ) -> anyhow::Result<Self> {
         let runtime = runtime::Builder::new_multi_thread()
             .thread_name("metrics-runtime")
             .worker_threads(2)
@@ -27,12 +33,17 @@ impl MetricsRuntime {
             .context("metrics runtime creation failed")?;
         let _guard = runtime.enter();
 
-        Self::new(metrics_address, Some(runtime))
+        Self::new(metrics_address, Some(runtime), tls_config, registry)
     }
 
     /// Create a new runtime for metrics and logging.
-    pub fn new(metrics_address: SocketAddr, runtime: Option<Runtime>) -> anyhow::Result<Self> {
-        let registry = start_prometheus_server(metrics_address);
+    pub fn new(
+        metrics_address: SocketAddr,
+        runtime: Option<Runtime>,
+        tls_config: Option<RustlsConfig>,
+        registry: Registry,
+    ) -> anyhow::Result<Self> {
+        let registry = start_prometheus_server(metrics_address, tls_config, registry);
 
         Ok(Self {
             runtime,
@@ -58,19 +69,29 @@ pub async fn metrics(
 
 // Creates a new http server that has as a sole purpose to expose
 // and endpoint that prometheus agent can use to poll for the metrics.
-// A RegistryService is returned that can be used to get access in prometheus Registries.
-pub fn start_prometheus_server(addr: SocketAddr) -> Registry {
-    let registry = Registry::new();
-
+// Takes the process-wide `registry` (the same one `make_reqwest_client`, `TokenRateLimiter`,
+// `UpstreamRouter`, etc. register their counters into) rather than creating its own, so
+// `/metrics` actually serves what the rest of the proxy publishes.
+pub fn start_prometheus_server(addr: SocketAddr, tls_config: Option<RustlsConfig>, registry: Registry) -> Registry {
     let app = Router::new()
         .route(METRICS_ROUTE, get(metrics))
         .layer(Extension(registry.clone()));
 
     tokio::spawn(async move {
-        let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-        axum::serve(listener, app.into_make_service())
-            .await
-            .unwrap();
+        match tls_config {
+            Some(tls_config) => {
+                axum_server::bind_rustls(addr, tls_config)
+                    .serve(app.into_make_service())
+                    .await
+                    .unwrap();
+            }
+            None => {
+                let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
+                axum::serve(listener, app.into_make_service())
+                    .await
+                    .unwrap();
+            }
+        }
     });
 
     registry
@@ -108,6 +129,8 @@ impl MetricPushRuntime {
             .context("metric push runtime creation failed")?;
         let _guard = runtime.enter();
 
+        let retry_metrics = RetryMetrics::new(&registry, "push");
+
         let metric_push_handle = tokio::spawn(async move {
             let mut interval = tokio::time::interval(mp_config.config.push_interval);
             interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
@@ -123,6 +146,8 @@ impl MetricPushRuntime {
                             &registry,
                             // clone because we serialize this with our metrics
                             mp_config.config.labels.clone(),
+                            &mp_config.config.retry,
+                            &retry_metrics,
                         ).await {
                             tracing::warn!(?error, "unable to push metrics");
                             client = create_push_client();
@@ -176,6 +201,8 @@ async fn push_metrics(
     push_url: &str,
     registry: &Registry,
     labels: Option<HashMap<String, String>>,
+    retry: &crate::config::RetryConfig,
+    retry_metrics: &RetryMetrics,
 ) -> Result<(), anyhow::Error> {
     tracing::debug!(push_url, "pushing metrics to remote");
 
@@ -206,13 +233,14 @@ async fn push_metrics(
         tracing::warn!(?error, "unable to snappy encode metrics");
     })?;
 
-    let response = client
-        .post(push_url)
-        .header(reqwest::header::AUTHORIZATION, bearer_token)
-        .header(reqwest::header::CONTENT_ENCODING, "snappy")
-        .body(compressed)
-        .send()
-        .await?;
+    let response = send_with_retry(retry, retry_metrics, || {
+        client
+            .post(push_url)
+            .header(reqwest::header::AUTHORIZATION, bearer_token)
+            .header(reqwest::header::CONTENT_ENCODING, "snappy")
+            .body(compressed.clone())
+    })
+    .await?;
 
     if !response.status().is_success() {
         let status = response.status();
@@ -1,9 +1,12 @@
 // Copyright (c), Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
+use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::Duration;
 
 use axum::{Extension, Router, extract::DefaultBodyLimit, middleware, routing::post};
+use axum_server::tls_rustls::RustlsConfig;
 use tokio::signal;
 use tower::ServiceBuilder;
 use tower_http::{
@@ -14,15 +17,20 @@ use tower_http::{
 use tracing::Level;
 use crate::handlers::relay_metrics_to_mimir;
 use crate::handlers::ReqwestClient;
-use crate::middleware::expect_valid_bearer_token;
+use crate::middleware::expect_valid_bearer_token_or_node_signature;
+use crate::node_auth::{MaxSkew, NodeRegistry};
+use crate::rate_limit::{enforce_rate_limit, TokenRateLimiter};
 use crate::var;
-use crate::allowers::BearerTokenProvider;
+use crate::{Allower, BearerToken};
 
 
 /// build our axum app
 pub fn app(
     reqwest_client: ReqwestClient,
-    allower: Option<BearerTokenProvider>,
+    allower: Option<Arc<dyn Allower<BearerToken>>>,
+    rate_limiter: Option<Arc<TokenRateLimiter>>,
+    node_registry: Option<Arc<dyn NodeRegistry>>,
+    node_signature_max_skew: Duration,
 ) -> Router {
     // build our application with a route and our sender mpsc
     let mut router = Router::new()
@@ -32,13 +40,27 @@ pub fn app(
             "MAX_BODY_SIZE",
             1024 * 1024 * 5
         )));
-    
-    // if we have an allower, add the middleware and extension
-    if let Some(allower) = allower {
-        router = router.route_layer(middleware::from_fn(expect_valid_bearer_token))
-            .layer(Extension(allower));
+
+    // rate limiting relies on the token name the auth middleware resolves, so it is added
+    // before (and therefore runs after) the auth layer below.
+    if let Some(rate_limiter) = rate_limiter {
+        router = router
+            .route_layer(middleware::from_fn(enforce_rate_limit))
+            .layer(Extension(rate_limiter));
+    }
+
+    // nodes may authenticate with a shared bearer token, a signed push with their on-chain
+    // secp256r1 key, or either -- a mixed fleet can have legacy nodes on tokens and newer nodes
+    // on signatures at the same time, so a single middleware accepts either rather than ANDing
+    // the two checks together (which would lock out whichever mechanism a node didn't present).
+    if allower.is_some() || node_registry.is_some() {
+        router = router
+            .route_layer(middleware::from_fn(expect_valid_bearer_token_or_node_signature))
+            .layer(Extension(allower))
+            .layer(Extension(node_registry))
+            .layer(Extension(MaxSkew(node_signature_max_skew)));
     }
-        
+
     router
         // Enforce on all routes.
         // If the request does not complete within the specified timeout it will be aborted
@@ -72,6 +94,25 @@ pub async fn server(listener: tokio::net::TcpListener, app: Router) -> std::io::
         .await
 }
 
+/// Serves `app` on `addr`, terminating TLS with `tls_config` when one is configured (see
+/// `tls::load_tls_config`); otherwise falls back to plaintext HTTP as before. This keeps the
+/// plaintext path as the default for deployments that terminate TLS at a sidecar/load balancer.
+pub async fn serve(addr: SocketAddr, app: Router, tls_config: Option<RustlsConfig>) -> std::io::Result<()> {
+    match tls_config {
+        Some(tls_config) => {
+            tracing::info!("listening on {} (tls)", addr);
+            axum_server::bind_rustls(addr, tls_config)
+                .serve(app.into_make_service())
+                .await
+        }
+        None => {
+            tracing::info!("listening on {}", addr);
+            let listener = tokio::net::TcpListener::bind(addr).await?;
+            server(listener, app).await
+        }
+    }
+}
+
 async fn shutdown_signal() {
     let ctrl_c = async {
         signal::ctrl_c()
@@ -0,0 +1,65 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use axum_server::tls_rustls::RustlsConfig;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::RootCertStore;
+
+use crate::config::ProxyConfig;
+
+/// Builds the rustls server config used to terminate TLS on the ingest/metrics listeners, backed
+/// by `tls_cert_path`/`tls_key_path`. When `tls_client_ca_path` is also set, client certificates
+/// are required and verified against that CA so only provisioned seal nodes can connect. Returns
+/// `None` when no cert/key pair is configured, in which case callers should fall back to
+/// plaintext HTTP.
+pub fn load_tls_config(config: &ProxyConfig) -> Result<Option<RustlsConfig>> {
+    let (Some(cert_path), Some(key_path)) = (&config.tls_cert_path, &config.tls_key_path) else {
+        return Ok(None);
+    };
+
+    let cert_chain = load_certs(cert_path)?;
+    let key = load_private_key(key_path)?;
+
+    let builder = rustls::ServerConfig::builder();
+    let builder = match &config.tls_client_ca_path {
+        Some(ca_path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(ca_path)? {
+                roots
+                    .add(cert)
+                    .context("invalid client CA certificate")?;
+            }
+            let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+                .build()
+                .context("unable to build client certificate verifier")?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let server_config = builder
+        .with_single_cert(cert_chain, key)
+        .context("invalid TLS certificate/key pair")?;
+
+    Ok(Some(RustlsConfig::from_config(Arc::new(server_config))))
+}
+
+fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>> {
+    let file = File::open(path).context(format!("cannot open cert file {path:?}"))?;
+    rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .context(format!("unable to parse certificates in {path:?}"))
+}
+
+fn load_private_key(path: &str) -> Result<PrivateKeyDer<'static>> {
+    let file = File::open(path).context(format!("cannot open key file {path:?}"))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .context(format!("unable to parse private key in {path:?}"))?
+        .context(format!("no private key found in {path:?}"))
+}
@@ -0,0 +1,209 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use axum::http::StatusCode;
+use prometheus::{
+    register_histogram_vec_with_registry, register_int_counter_vec_with_registry, HistogramVec,
+    IntCounterVec, Registry,
+};
+
+use crate::config::{UpstreamConfig, UpstreamMode};
+use crate::retry::{send_with_retry, RetryMetrics};
+
+/// how many consecutive failures mark a target unhealthy.
+const UNHEALTHY_THRESHOLD: u32 = 3;
+const BASE_COOLDOWN: Duration = Duration::from_secs(5);
+const MAX_COOLDOWN: Duration = Duration::from_secs(300);
+
+/// tracks consecutive failures for a single upstream target and derives an exponentially
+/// increasing cooldown before we probe it again once it has tripped unhealthy.
+#[derive(Debug, Default)]
+struct Health {
+    consecutive_failures: AtomicU32,
+    unhealthy_until_ms: AtomicU64,
+}
+
+impl Health {
+    fn is_healthy(&self) -> bool {
+        now_ms() >= self.unhealthy_until_ms.load(Ordering::Relaxed)
+    }
+
+    fn record_success(&self) {
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+        self.unhealthy_until_ms.store(0, Ordering::Relaxed);
+    }
+
+    fn record_failure(&self) {
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures >= UNHEALTHY_THRESHOLD {
+            let exponent = (failures - UNHEALTHY_THRESHOLD).min(10);
+            let cooldown = BASE_COOLDOWN.saturating_mul(1 << exponent).min(MAX_COOLDOWN);
+            self.unhealthy_until_ms
+                .store(now_ms() + cooldown.as_millis() as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64
+}
+
+struct Target {
+    url: String,
+    weight: u32,
+    mode: UpstreamMode,
+    health: Health,
+}
+
+/// routes a relayed push across a configurable set of upstream targets: `Mirror` targets all
+/// receive a copy of every push, while `Failover` targets are tried in descending-weight
+/// (priority) order, stopping at the first success. A per-target health record is used to skip
+/// targets in an exponentially increasing cooldown after repeated failures.
+pub struct UpstreamRouter {
+    targets: Vec<Target>,
+    success: IntCounterVec,
+    failure: IntCounterVec,
+    latency: HistogramVec,
+}
+
+impl UpstreamRouter {
+    pub fn new(configs: &[UpstreamConfig], registry: &Registry) -> Self {
+        let targets = configs
+            .iter()
+            .map(|c| Target {
+                url: c.url.clone(),
+                weight: c.weight,
+                mode: c.mode,
+                health: Health::default(),
+            })
+            .collect();
+
+        let success = register_int_counter_vec_with_registry!(
+            "upstream_push_success_total",
+            "successful pushes to an upstream remote-write target",
+            &["url"],
+            registry
+        )
+        .expect("unable to register upstream_push_success_total");
+        let failure = register_int_counter_vec_with_registry!(
+            "upstream_push_failure_total",
+            "failed pushes to an upstream remote-write target",
+            &["url"],
+            registry
+        )
+        .expect("unable to register upstream_push_failure_total");
+        let latency = register_histogram_vec_with_registry!(
+            "upstream_push_latency_seconds",
+            "latency of pushes to an upstream remote-write target",
+            &["url"],
+            registry
+        )
+        .expect("unable to register upstream_push_latency_seconds");
+
+        Self { targets, success, failure, latency }
+    }
+
+    /// sends `body` to every configured target, fanning out to all `Mirror` targets and failing
+    /// over across `Failover` targets in priority order. Returns the first successful failover
+    /// response body, and an error when every failover target was exhausted. For a mirror-only
+    /// deployment (no `Failover` targets configured), succeeds if at least one mirror accepted the
+    /// push and fails only when every mirror did, so a caller never reports delivery as
+    /// successful when nothing actually got through.
+    pub async fn relay(
+        &self,
+        client: &reqwest::Client,
+        retry: &crate::config::RetryConfig,
+        retry_metrics: &RetryMetrics,
+        body: &[u8],
+    ) -> Result<String, StatusCode> {
+        let mirrors: Vec<&Target> = self.targets.iter().filter(|t| t.mode == UpstreamMode::Mirror).collect();
+        let mut any_mirror_success = false;
+        let mut last_mirror_error = StatusCode::BAD_GATEWAY;
+        for target in &mirrors {
+            // mirrors are best-effort: an individual mirror failure doesn't fail the push by
+            // itself. When failover targets are configured the outcome is theirs alone; otherwise
+            // the push succeeds if any mirror accepted it.
+            match self.send_to(target, client, retry, retry_metrics, body).await {
+                Ok(_) => any_mirror_success = true,
+                Err(status) => last_mirror_error = status,
+            }
+        }
+
+        let mut failover_targets: Vec<&Target> =
+            self.targets.iter().filter(|t| t.mode == UpstreamMode::Failover).collect();
+        if failover_targets.is_empty() {
+            if mirrors.is_empty() {
+                // zero upstreams configured at all -- a misconfiguration, not a mirror-only
+                // deployment with nothing to mirror to, so this must not report success.
+                tracing::error!("no upstream targets configured; refusing to report delivery as successful");
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+            if any_mirror_success {
+                return Ok(String::new());
+            }
+            return Err(last_mirror_error);
+        }
+        failover_targets.sort_by(|a, b| b.weight.cmp(&a.weight));
+
+        // prefer healthy targets, but fall back to trying everything if all are in cooldown so
+        // we still probe for recovery instead of failing outright.
+        let healthy: Vec<&&Target> = failover_targets.iter().filter(|t| t.health.is_healthy()).collect();
+        let ordered: Vec<&Target> = if healthy.is_empty() {
+            failover_targets.clone()
+        } else {
+            healthy.into_iter().copied().collect()
+        };
+
+        let mut last_error = StatusCode::BAD_GATEWAY;
+        for target in ordered {
+            match self.send_to(target, client, retry, retry_metrics, body).await {
+                Ok(text) => return Ok(text),
+                Err(status) => last_error = status,
+            }
+        }
+        Err(last_error)
+    }
+
+    async fn send_to(
+        &self,
+        target: &Target,
+        client: &reqwest::Client,
+        retry: &crate::config::RetryConfig,
+        retry_metrics: &RetryMetrics,
+        body: &[u8],
+    ) -> Result<String, StatusCode> {
+        let started = Instant::now();
+        let result = send_with_retry(retry, retry_metrics, || {
+            client
+                .post(&target.url)
+                .header(reqwest::header::CONTENT_ENCODING, "snappy")
+                .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+                .body(body.to_vec())
+        })
+        .await;
+        self.latency.with_label_values(&[&target.url]).observe(started.elapsed().as_secs_f64());
+
+        match result {
+            Ok(response) if response.status().is_success() => {
+                self.success.with_label_values(&[&target.url]).inc();
+                target.health.record_success();
+                Ok(response.text().await.unwrap_or_default())
+            }
+            Ok(response) => {
+                tracing::warn!(url = %target.url, status = %response.status(), "upstream push rejected");
+                self.failure.with_label_values(&[&target.url]).inc();
+                target.health.record_failure();
+                Err(StatusCode::BAD_GATEWAY)
+            }
+            Err(error) => {
+                tracing::warn!(url = %target.url, %error, "upstream push failed");
+                self.failure.with_label_values(&[&target.url]).inc();
+                target.health.record_failure();
+                Err(StatusCode::BAD_GATEWAY)
+            }
+        }
+    }
+}